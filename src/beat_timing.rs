@@ -1,11 +1,45 @@
 use std::collections::HashSet;
 
+/// How tightly a press landed relative to a beat.
+///
+/// Ordered loosest-to-tightest so `Grade::Good < Grade::Perfect` etc. reads naturally,
+/// and so a `min_accept_grade` comparison (`grade >= min_accept_grade`) does what it looks like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Grade {
+    Miss,
+    Good,
+    Great,
+    Perfect,
+}
+
+/// Whether a press landed before or after the beat it was judged against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Early,
+    Late,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct BeatConfig {
     pub bpm: f32,
     pub offset_seconds: f32,
-    pub leniency_seconds: f32,
     pub grace_beats: usize,
+    /// Concentric timing windows, ascending by half-width in seconds, e.g.
+    /// `[(Grade::Perfect, 0.03), (Grade::Great, 0.06), (Grade::Good, 0.1)]`.
+    ///
+    /// `judge` walks these in order and returns the tightest window whose half-width
+    /// contains `error.abs()`; if none does, the press grades as `Grade::Miss`.
+    pub windows: &'static [(Grade, f32)],
+    /// The minimum grade that still counts as "on beat" (resolves the beat, keeps combo alive).
+    pub min_accept_grade: Grade,
+    /// Clock deltas at or under this many seconds (vs. the previous observed position)
+    /// classify as `ClockDelta::OnTime`; larger ones, up to
+    /// `clock_discontinuity_threshold_seconds`, classify as `ClockDelta::Jitter`. Neither
+    /// triggers a resync, and neither adjusts the position the tracker is given.
+    pub clock_jitter_threshold_seconds: f32,
+    /// Clock deltas over this many seconds (forward or backward) are treated as a
+    /// discontinuity — a seek or a pause/resume stall — and trigger a resync.
+    pub clock_discontinuity_threshold_seconds: f32,
 }
 
 impl BeatConfig {
@@ -18,6 +52,15 @@ impl BeatConfig {
         (beat_index as f32) * self.beat_period_seconds() - self.offset_seconds
     }
 
+    /// Half-width (in seconds) of the loosest configured window, i.e. how far past a beat
+    /// a press can still land before it's unrecoverably a miss.
+    pub fn max_window_seconds(&self) -> f32 {
+        self.windows
+            .last()
+            .map(|(_, half_width)| *half_width)
+            .unwrap_or(0.0)
+    }
+
     /// Judges the closest beat to `elapsed_seconds`.
     ///
     /// `error_seconds` is signed:
@@ -26,23 +69,43 @@ impl BeatConfig {
     pub fn judge(&self, elapsed_seconds: f32) -> Judgment {
         let beat_period = self.beat_period_seconds();
 
-        // This matches your previous logic: shift by half a beat so that modulo math
-        // picks the nearest beat rather than the previous beat.
+        // Shift by half a beat so that modulo math picks the nearest beat rather than the
+        // previous beat: the beat itself then sits at `phase == beat_period / 2`, not at
+        // `phase == 0`, so distance-to-beat is distance to the center, not to the edges.
         let phase = (elapsed_seconds + self.offset_seconds + (beat_period / 2.0)) % beat_period;
 
-        let mut error = phase.min(beat_period - phase);
-        let on_beat = error <= self.leniency_seconds;
+        let mut error = (phase - beat_period / 2.0).abs();
+
+        let grade = self
+            .windows
+            .iter()
+            .find(|(_, half_width)| error <= *half_width)
+            .map(|(grade, _)| *grade)
+            .unwrap_or(Grade::Miss);
+        let on_beat = grade >= self.min_accept_grade;
 
         if phase > beat_period / 2.0 {
             error = -error;
         }
 
+        // The beat sits at `phase == beat_period / 2`, so a larger phase means the press
+        // came after it.
+        let timing = if phase > beat_period / 2.0 {
+            Some(Timing::Late)
+        } else if phase < beat_period / 2.0 {
+            Some(Timing::Early)
+        } else {
+            None
+        };
+
         // Use the same indexing semantics you already had.
         let beat_index = ((elapsed_seconds + self.offset_seconds) / beat_period).round() as usize;
 
         Judgment {
             beat_index,
             on_beat,
+            grade,
+            timing,
             error_seconds: error,
         }
     }
@@ -52,6 +115,8 @@ impl BeatConfig {
 pub struct Judgment {
     pub beat_index: usize,
     pub on_beat: bool,
+    pub grade: Grade,
+    pub timing: Option<Timing>,
     pub error_seconds: f32,
 }
 
@@ -91,6 +156,22 @@ pub struct BeatTracker {
     resolved_beats: HashSet<usize>,
     // Next beat index we should consider for missed-beat detection.
     next_miss_check: usize,
+    // Last `elapsed_seconds` we were asked to classify, for drift detection.
+    last_elapsed_seconds: Option<f32>,
+}
+
+/// How the audio clock's latest position compared to the last one we observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockDelta {
+    /// Advanced (or rewound) by at most `clock_jitter_threshold_seconds`, as expected.
+    OnTime,
+    /// Advanced (or rewound) by more than `clock_jitter_threshold_seconds` but at most
+    /// `clock_discontinuity_threshold_seconds`. Not resynced; the position is used as
+    /// observed, without smoothing.
+    Jitter,
+    /// Jumped by more than `clock_discontinuity_threshold_seconds`, forward or backward —
+    /// almost certainly a seek or a pause/resume stall. Triggers a resync.
+    Discontinuity,
 }
 
 impl BeatTracker {
@@ -99,6 +180,7 @@ impl BeatTracker {
             config,
             resolved_beats: HashSet::new(),
             next_miss_check: config.grace_beats,
+            last_elapsed_seconds: None,
         }
     }
 
@@ -110,6 +192,51 @@ impl BeatTracker {
         self.config = config;
         self.resolved_beats.clear();
         self.next_miss_check = config.grace_beats;
+        self.last_elapsed_seconds = None;
+    }
+
+    /// Classify the latest audio-clock position against the last one observed, and resync
+    /// on a large discontinuity (backward jump or forward gap) so it doesn't corrupt
+    /// `beat_index` math or spuriously flood `poll_missed`. Call this once per frame with
+    /// the same `elapsed_seconds` you're about to hand to `register_press`/`poll_missed`.
+    pub fn observe_clock(&mut self, elapsed_seconds: f32) -> ClockDelta {
+        let delta = match self.last_elapsed_seconds {
+            Some(prev) => elapsed_seconds - prev,
+            None => 0.0,
+        };
+        self.last_elapsed_seconds = Some(elapsed_seconds);
+
+        if delta.abs() > self.config.clock_discontinuity_threshold_seconds {
+            self.resync(elapsed_seconds);
+            ClockDelta::Discontinuity
+        } else if delta.abs() > self.config.clock_jitter_threshold_seconds {
+            ClockDelta::Jitter
+        } else {
+            ClockDelta::OnTime
+        }
+    }
+
+    /// Recompute `next_miss_check` from a new clock position after a discontinuity, and
+    /// forget any beats ahead of it that were previously marked resolved — a backward seek
+    /// means the player is about to replay them, so they shouldn't count as already-hit or
+    /// already-missed. A forward jump needs no such cleanup: `next_miss_check` simply skips
+    /// past the gap, so the skipped beats are never visited by `poll_missed` and never emit
+    /// phantom misses.
+    fn resync(&mut self, elapsed_seconds: f32) {
+        let beat_period = self.config.beat_period_seconds();
+        let new_position = if beat_period.is_finite() && beat_period > 0.0 {
+            (((elapsed_seconds + self.config.offset_seconds) / beat_period).floor().max(0.0)
+                as usize)
+                .max(self.config.grace_beats)
+        } else {
+            self.config.grace_beats
+        };
+
+        if new_position < self.next_miss_check {
+            self.resolved_beats.retain(|&beat_index| beat_index < new_position);
+        }
+
+        self.next_miss_check = new_position;
     }
 
     /// Register a button press at `elapsed_seconds`.
@@ -145,14 +272,15 @@ impl BeatTracker {
     pub fn poll_missed(&mut self, elapsed_seconds: f32) -> Vec<MissedBeat> {
         let mut missed = Vec::new();
         let beat_period = self.config.beat_period_seconds();
-        let leniency = self.config.leniency_seconds;
+        let max_window = self.config.max_window_seconds();
 
         loop {
             let beat_index = self.next_miss_check;
             let beat_time = self.config.beat_time_seconds(beat_index);
 
-            // Once we are past (beat_time + leniency), that beat can no longer be hit.
-            if elapsed_seconds <= beat_time + leniency {
+            // Once we are past (beat_time + max_window), that beat can no longer be hit
+            // at any grade.
+            if elapsed_seconds <= beat_time + max_window {
                 break;
             }
 
@@ -178,4 +306,375 @@ impl BeatTracker {
 
         missed
     }
+
+    /// Every countable beat (i.e. not within `grace_beats`) whose `beat_time_seconds`
+    /// falls in `(elapsed_seconds, elapsed_seconds + horizon_seconds)`, without resolving
+    /// it. Lets callers run ahead of the audio clock to schedule visuals or a metronome
+    /// click before the beat actually lands.
+    pub fn upcoming(&self, elapsed_seconds: f32, horizon_seconds: f32) -> Vec<UpcomingBeat> {
+        let beat_period = self.config.beat_period_seconds();
+        if !beat_period.is_finite() || beat_period <= 0.0 || horizon_seconds <= 0.0 {
+            return Vec::new();
+        }
+
+        // Same beat-index formula as `BeatConfig::judge`, but floored rather than rounded so
+        // the seed never lands past a beat still ahead of `elapsed_seconds`; used here to seed the
+        // search near `elapsed_seconds` instead of scanning from beat zero every call.
+        let estimated_index =
+            ((elapsed_seconds + self.config.offset_seconds) / beat_period).floor();
+        let mut beat_index = (estimated_index.max(0.0) as usize).max(self.config.grace_beats);
+
+        let mut upcoming = Vec::new();
+        loop {
+            let beat_time = self.config.beat_time_seconds(beat_index);
+            if beat_time >= elapsed_seconds + horizon_seconds {
+                break;
+            }
+            if beat_time > elapsed_seconds {
+                upcoming.push(UpcomingBeat {
+                    beat_index,
+                    beat_time_seconds: beat_time,
+                    time_until_seconds: beat_time - elapsed_seconds,
+                });
+            }
+            beat_index += 1;
+        }
+        upcoming
+    }
+}
+
+/// A beat that hasn't happened yet, as returned by [`BeatTracker::upcoming`]. Unlike
+/// [`MissedBeat`], this doesn't resolve the beat — it's purely informational, for
+/// anticipatory UI (a shrinking ring, a metronome click) that needs to run ahead of the
+/// audio clock.
+#[derive(Debug, Clone, Copy)]
+pub struct UpcomingBeat {
+    pub beat_index: usize,
+    pub beat_time_seconds: f32,
+    pub time_until_seconds: f32,
+}
+
+/// A [`MissedBeat`] tagged with the lane it fell out of, as emitted by
+/// [`MultiTrackBeat::poll_missed`].
+///
+/// Not yet constructed from `main` — no song currently binds different `Move` keys to
+/// different lanes, which is its own chunk of gameplay/input-mapping work beyond this
+/// module — so this and `MultiTrackBeat` are unused outside of their own tests for now.
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct LaneMissedBeat {
+    pub lane: String,
+    pub missed: MissedBeat,
+}
+
+/// Several independent [`BeatTracker`]s, one per named rhythmic lane, so a song can have
+/// e.g. a kick pattern on quarter notes and a hi-hat pattern on eighth-note triplets at
+/// the same time. Each lane keeps its own config (BPM subdivision, offset, windows) and
+/// its own resolved-beats/next-miss-check state.
+#[derive(Debug, Clone, Default)]
+#[allow(dead_code)]
+pub struct MultiTrackBeat {
+    lanes: Vec<(String, BeatTracker)>,
+}
+
+#[allow(dead_code)]
+impl MultiTrackBeat {
+    pub fn new() -> Self {
+        Self { lanes: Vec::new() }
+    }
+
+    /// Register a lane under `name`, tracked independently with its own `config`.
+    pub fn add_lane(&mut self, name: impl Into<String>, config: BeatConfig) {
+        self.lanes.push((name.into(), BeatTracker::new(config)));
+    }
+
+    pub fn lane(&self, name: &str) -> Option<&BeatTracker> {
+        self.lanes.iter().find(|(n, _)| n == name).map(|(_, t)| t)
+    }
+
+    /// Register a press against a single named lane. Returns `None` if no lane by that
+    /// name was registered.
+    pub fn register_press(&mut self, lane: &str, elapsed_seconds: f32) -> Option<PressResult> {
+        self.lanes
+            .iter_mut()
+            .find(|(name, _)| name == lane)
+            .map(|(_, tracker)| tracker.register_press(elapsed_seconds))
+    }
+
+    /// Poll every lane for newly-missed beats and merge them into a single time-ordered
+    /// stream, like a k-way merge over per-lane event lists with per-lane tick lengths.
+    pub fn poll_missed(&mut self, elapsed_seconds: f32) -> Vec<LaneMissedBeat> {
+        let mut per_lane: Vec<(&str, std::vec::IntoIter<MissedBeat>)> = self
+            .lanes
+            .iter_mut()
+            .map(|(name, tracker)| {
+                (name.as_str(), tracker.poll_missed(elapsed_seconds).into_iter())
+            })
+            .collect();
+
+        // Each lane's list is already time-ordered ascending by beat time (poll_missed
+        // walks beat indices in order), so the beats with the largest late_by_seconds
+        // happened earliest. Merge the per-lane heads like any k-way merge of sorted lists.
+        let mut heads: Vec<Option<MissedBeat>> =
+            per_lane.iter_mut().map(|(_, iter)| iter.next()).collect();
+
+        let mut merged = Vec::new();
+        loop {
+            let next_lane = heads
+                .iter()
+                .enumerate()
+                .filter_map(|(i, head)| head.map(|m| (i, m.late_by_seconds)))
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(i, _)| i);
+
+            let Some(i) = next_lane else { break };
+            let missed = heads[i].take().unwrap();
+            merged.push(LaneMissedBeat {
+                lane: per_lane[i].0.to_string(),
+                missed,
+            });
+            heads[i] = per_lane[i].1.next();
+        }
+
+        merged
+    }
+}
+
+/// Per-song accuracy/scoring accumulator fed by [`PressResult`]s and [`MissedBeat`]s.
+///
+/// Accumulation is O(1) per event; the derived metrics (mean/std-dev error, weighted
+/// accuracy) are computed lazily by [`ScoreTracker::summary`] rather than every frame.
+#[derive(Debug, Clone, Default)]
+pub struct ScoreTracker {
+    on_beat_hits: usize,
+    wrong_presses: usize,
+    misses: usize,
+    early_errors_seconds: Vec<f32>,
+    late_errors_seconds: Vec<f32>,
+    grade_hits: Vec<Grade>,
+    combo: usize,
+    longest_combo: usize,
+}
+
+impl ScoreTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a press result. Duplicate presses (a second press on an already-resolved
+    /// beat) don't count twice and don't affect the combo.
+    ///
+    /// `matches_chart` is whether the pressed `Move` was the one a loaded chart expects
+    /// for this beat; pass `true` when there's no chart (any move is accepted).
+    pub fn record_press(&mut self, result: &PressResult, matches_chart: bool) {
+        if !result.counts || result.accept == PressAccept::Duplicate {
+            return;
+        }
+
+        if result.judgment.on_beat && matches_chart {
+            self.on_beat_hits += 1;
+            self.grade_hits.push(result.judgment.grade);
+            match result.judgment.timing {
+                Some(Timing::Early) => self.early_errors_seconds.push(result.judgment.error_seconds),
+                Some(Timing::Late) => self.late_errors_seconds.push(result.judgment.error_seconds),
+                None => {}
+            }
+            self.combo += 1;
+            self.longest_combo = self.longest_combo.max(self.combo);
+        } else {
+            self.wrong_presses += 1;
+            self.combo = 0;
+        }
+    }
+
+    /// Feed a beat that closed out with no accepted press.
+    pub fn record_miss(&mut self, _miss: &MissedBeat) {
+        self.misses += 1;
+        self.combo = 0;
+    }
+
+    pub fn combo(&self) -> usize {
+        self.combo
+    }
+
+    pub fn longest_combo(&self) -> usize {
+        self.longest_combo
+    }
+
+    /// Compute the final accuracy summary. Intended to be called once, at song end.
+    pub fn summary(&self) -> ScoreSummary {
+        let all_errors = self
+            .early_errors_seconds
+            .iter()
+            .chain(self.late_errors_seconds.iter());
+        let sample_count = self.early_errors_seconds.len() + self.late_errors_seconds.len();
+
+        let mean_abs_error_seconds = if sample_count == 0 {
+            0.0
+        } else {
+            all_errors.clone().map(|e| e.abs()).sum::<f32>() / sample_count as f32
+        };
+
+        let error_std_dev_seconds = if sample_count == 0 {
+            0.0
+        } else {
+            let mean_error =
+                all_errors.clone().sum::<f32>() / sample_count as f32;
+            let variance = all_errors
+                .map(|e| (e - mean_error).powi(2))
+                .sum::<f32>()
+                / sample_count as f32;
+            variance.sqrt()
+        };
+
+        let total_judged = self.on_beat_hits + self.wrong_presses + self.misses;
+        let accuracy_percent = if total_judged == 0 {
+            0.0
+        } else {
+            let weighted: f32 = self.grade_hits.iter().map(|g| grade_weight(*g)).sum();
+            100.0 * weighted / total_judged as f32
+        };
+
+        ScoreSummary {
+            on_beat_hits: self.on_beat_hits,
+            wrong_presses: self.wrong_presses,
+            misses: self.misses,
+            mean_abs_error_seconds,
+            error_std_dev_seconds,
+            accuracy_percent,
+            combo: self.combo,
+            longest_combo: self.longest_combo,
+        }
+    }
+}
+
+/// Weight (out of 100) a grade contributes towards the accuracy percentage.
+fn grade_weight(grade: Grade) -> f32 {
+    match grade {
+        Grade::Perfect => 100.0,
+        Grade::Great => 80.0,
+        Grade::Good => 50.0,
+        Grade::Miss => 0.0,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreSummary {
+    pub on_beat_hits: usize,
+    pub wrong_presses: usize,
+    pub misses: usize,
+    pub mean_abs_error_seconds: f32,
+    pub error_std_dev_seconds: f32,
+    /// 0-100, weighted by grade (a run of `Perfect`s trends towards 100).
+    pub accuracy_percent: f32,
+    pub combo: usize,
+    pub longest_combo: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BeatConfig {
+        BeatConfig {
+            bpm: 132.0,
+            offset_seconds: 0.04,
+            grace_beats: 8,
+            windows: &[(Grade::Perfect, 0.03), (Grade::Great, 0.06), (Grade::Good, 0.1)],
+            min_accept_grade: Grade::Good,
+            clock_jitter_threshold_seconds: 0.05,
+            clock_discontinuity_threshold_seconds: 0.25,
+        }
+    }
+
+    #[test]
+    fn judge_grades_an_exact_beat_as_perfect() {
+        let config = config();
+        let beat_time = config.beat_time_seconds(10);
+
+        let judgment = config.judge(beat_time);
+
+        assert_eq!(judgment.beat_index, 10);
+        assert_eq!(judgment.grade, Grade::Perfect);
+        assert!(judgment.error_seconds.abs() < 1e-4);
+    }
+
+    #[test]
+    fn judge_grades_a_half_period_off_press_as_a_miss() {
+        let config = config();
+        let beat_time = config.beat_time_seconds(10);
+        let half_period = config.beat_period_seconds() / 2.0;
+
+        let judgment = config.judge(beat_time + half_period);
+
+        assert_eq!(judgment.grade, Grade::Miss);
+    }
+
+    #[test]
+    fn multi_track_beat_merges_missed_beats_across_lanes_in_time_order() {
+        let kick = BeatConfig {
+            bpm: 120.0,
+            ..config()
+        };
+        let hi_hat = BeatConfig {
+            bpm: 240.0,
+            ..config()
+        };
+
+        let mut multi = MultiTrackBeat::new();
+        multi.add_lane("kick", kick);
+        multi.add_lane("hi_hat", hi_hat);
+
+        // Run the clock well past both lanes' first few countable beats (after
+        // `grace_beats`) without ever pressing anything, so every one of them comes back as missed.
+        let elapsed =
+            kick.beat_time_seconds(kick.grace_beats + 4) + kick.max_window_seconds() + 1.0;
+        let missed = multi.poll_missed(elapsed);
+
+        assert!(missed.iter().any(|m| m.lane == "kick"));
+        assert!(missed.iter().any(|m| m.lane == "hi_hat"));
+        // Merged in time order: late_by_seconds should be non-increasing.
+        for pair in missed.windows(2) {
+            assert!(pair[0].missed.late_by_seconds >= pair[1].missed.late_by_seconds);
+        }
+    }
+
+    #[test]
+    fn score_tracker_summary_reports_perfect_accuracy_for_all_perfect_hits() {
+        let config = config();
+        let mut tracker = BeatTracker::new(config);
+        let mut score = ScoreTracker::new();
+
+        for beat_index in config.grace_beats..config.grace_beats + 4 {
+            let press = tracker.register_press(config.beat_time_seconds(beat_index));
+            score.record_press(&press, true);
+        }
+
+        let summary = score.summary();
+        assert_eq!(summary.on_beat_hits, 4);
+        assert_eq!(summary.misses, 0);
+        assert_eq!(summary.wrong_presses, 0);
+        assert_eq!(summary.longest_combo, 4);
+        assert_eq!(summary.accuracy_percent, 100.0);
+        assert!(summary.mean_abs_error_seconds < 1e-4);
+    }
+
+    #[test]
+    fn score_tracker_resets_combo_on_a_wrong_move_but_keeps_the_longest() {
+        let config = config();
+        let mut tracker = BeatTracker::new(config);
+        let mut score = ScoreTracker::new();
+
+        for beat_index in config.grace_beats..config.grace_beats + 3 {
+            let press = tracker.register_press(config.beat_time_seconds(beat_index));
+            score.record_press(&press, true);
+        }
+        // A press that's on-beat but doesn't match the chart still breaks the combo.
+        let press = tracker.register_press(config.beat_time_seconds(config.grace_beats + 3));
+        score.record_press(&press, false);
+
+        assert_eq!(score.combo(), 0);
+        assert_eq!(score.longest_combo(), 3);
+        assert_eq!(score.summary().wrong_presses, 1);
+    }
 }
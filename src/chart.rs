@@ -0,0 +1,329 @@
+//! Loads a Standard MIDI File into a schedule of `(beat_index, expected Move)` entries, so
+//! a song can have an authored beatmap instead of accepting any `Move` on any beat.
+//!
+//! This hand-rolls just enough of the SMF spec (header + track chunks, variable-length
+//! quantities, running status, note-on/tempo meta events) to read a chart exported from a
+//! DAW or a simple MIDI editor — it isn't a general-purpose MIDI library.
+//!
+//! `main::load_chart_for` reads the `.mid` file under `assets/charts/` that shares the stem
+//! of a song's audio asset and calls into
+//! here to build that song's `Chart`, so a beatmap can be authored instead of accepting any
+//! `Move` on any beat.
+
+use crate::beat_timing::BeatConfig;
+use crate::Move;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartNote {
+    pub beat_index: usize,
+    pub expected_move: Move,
+}
+
+/// A beatmap: which `Move` is expected on which beat, as authored in a MIDI chart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chart {
+    notes: Vec<ChartNote>,
+}
+
+impl Chart {
+    pub fn expected_move(&self, beat_index: usize) -> Option<&Move> {
+        self.notes
+            .iter()
+            .find(|note| note.beat_index == beat_index)
+            .map(|note| &note.expected_move)
+    }
+
+    pub fn notes(&self) -> &[ChartNote] {
+        &self.notes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartLoadError {
+    NotAMidiFile,
+    UnexpectedEof,
+    /// We only support the common tick-based (pulses-per-quarter-note) time division,
+    /// not SMPTE frame-based division.
+    UnsupportedTimeDivision,
+}
+
+/// Parse a Standard MIDI File into a [`Chart`], via `note_to_move` to map MIDI note
+/// numbers to [`Move`] variants (notes that don't map are simply not charted) and `config`
+/// to convert each note's wall-clock time into a beat index on the song's existing grid.
+pub fn load_midi_chart(
+    bytes: &[u8],
+    note_to_move: impl Fn(u8) -> Option<Move>,
+    config: &BeatConfig,
+) -> Result<Chart, ChartLoadError> {
+    let mut reader = Reader::new(bytes);
+
+    if reader.take(4)? != b"MThd" {
+        return Err(ChartLoadError::NotAMidiFile);
+    }
+    let header_len = reader.take_u32()?;
+    let header_end = reader.pos + header_len as usize;
+    let _format = reader.take_u16()?;
+    let track_count = reader.take_u16()?;
+    let division = reader.take_u16()?;
+    if division & 0x8000 != 0 {
+        return Err(ChartLoadError::UnsupportedTimeDivision);
+    }
+    reader.pos = header_end;
+
+    let mut tracks = Vec::with_capacity(track_count as usize);
+    for _ in 0..track_count {
+        if reader.take(4)? != b"MTrk" {
+            return Err(ChartLoadError::NotAMidiFile);
+        }
+        let track_len = reader.take_u32()?;
+        let track_end = reader.pos + track_len as usize;
+        tracks.push(parse_track_events(&mut reader, track_end)?);
+        reader.pos = track_end;
+    }
+
+    let tempo_map = TempoMap::build(&tracks);
+
+    let mut notes = Vec::new();
+    for track in &tracks {
+        for event in track {
+            if let TrackEvent::NoteOn { tick, note } = event {
+                let Some(mv) = note_to_move(*note) else {
+                    continue;
+                };
+                let elapsed_seconds = tempo_map.seconds_for_tick(*tick, division);
+                let beat_index = ((elapsed_seconds as f32 + config.offset_seconds)
+                    / config.beat_period_seconds())
+                .round()
+                .max(0.0) as usize;
+                notes.push(ChartNote {
+                    beat_index,
+                    expected_move: mv,
+                });
+            }
+        }
+    }
+    notes.sort_by_key(|note| note.beat_index);
+
+    Ok(Chart { notes })
+}
+
+enum TrackEvent {
+    NoteOn { tick: u64, note: u8 },
+    Tempo { tick: u64, micros_per_quarter: u32 },
+}
+
+fn parse_track_events(reader: &mut Reader, track_end: usize) -> Result<Vec<TrackEvent>, ChartLoadError> {
+    let mut events = Vec::new();
+    let mut tick: u64 = 0;
+    let mut running_status: Option<u8> = None;
+
+    while reader.pos < track_end {
+        tick += reader.take_vlq()?;
+        let mut status = reader.peek_u8()?;
+
+        if status < 0x80 {
+            // Running status: reuse the last channel-event status byte, and this byte is
+            // actually the first data byte.
+            status = running_status.ok_or(ChartLoadError::UnexpectedEof)?;
+        } else {
+            reader.pos += 1;
+        }
+
+        match status {
+            0xFF => {
+                let meta_type = reader.take_u8()?;
+                let len = reader.take_vlq()? as usize;
+                let data = reader.take(len)?;
+                if meta_type == 0x51 && data.len() == 3 {
+                    let micros_per_quarter =
+                        ((data[0] as u32) << 16) | ((data[1] as u32) << 8) | data[2] as u32;
+                    events.push(TrackEvent::Tempo {
+                        tick,
+                        micros_per_quarter,
+                    });
+                }
+                running_status = None;
+            }
+            0xF0 | 0xF7 => {
+                let len = reader.take_vlq()? as usize;
+                reader.take(len)?;
+                running_status = None;
+            }
+            _ => {
+                running_status = Some(status);
+                let kind = status & 0xF0;
+                let param_count = match kind {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+                let params = reader.take(param_count)?;
+                if kind == 0x90 && params[1] > 0 {
+                    events.push(TrackEvent::NoteOn {
+                        tick,
+                        note: params[0],
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+/// Piecewise-constant tempo over the track, built from every `Tempo` meta event across all
+/// tracks (format-1 files conventionally put these on a dedicated tempo track).
+struct TempoMap {
+    // (tick, micros per quarter note), sorted ascending, always starts with tick 0.
+    changes: Vec<(u64, u32)>,
+}
+
+const DEFAULT_MICROS_PER_QUARTER: u32 = 500_000; // 120 BPM
+
+impl TempoMap {
+    fn build(tracks: &[Vec<TrackEvent>]) -> Self {
+        let mut changes: Vec<(u64, u32)> = tracks
+            .iter()
+            .flatten()
+            .filter_map(|event| match event {
+                TrackEvent::Tempo {
+                    tick,
+                    micros_per_quarter,
+                } => Some((*tick, *micros_per_quarter)),
+                _ => None,
+            })
+            .collect();
+        changes.sort_by_key(|(tick, _)| *tick);
+        if changes.first().map(|(tick, _)| *tick) != Some(0) {
+            changes.insert(0, (0, DEFAULT_MICROS_PER_QUARTER));
+        }
+        Self { changes }
+    }
+
+    fn seconds_for_tick(&self, tick: u64, division: u16) -> f64 {
+        let mut elapsed = 0.0;
+        let mut last_tick = 0;
+        let mut micros_per_quarter = DEFAULT_MICROS_PER_QUARTER;
+
+        for &(change_tick, change_micros) in &self.changes {
+            if change_tick >= tick {
+                break;
+            }
+            elapsed += seconds_between(last_tick, change_tick, micros_per_quarter, division);
+            last_tick = change_tick;
+            micros_per_quarter = change_micros;
+        }
+        elapsed + seconds_between(last_tick, tick, micros_per_quarter, division)
+    }
+}
+
+fn seconds_between(from_tick: u64, to_tick: u64, micros_per_quarter: u32, division: u16) -> f64 {
+    (to_tick - from_tick) as f64 * micros_per_quarter as f64 / 1_000_000.0 / division as f64
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ChartLoadError> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or(ChartLoadError::UnexpectedEof)?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn peek_u8(&self) -> Result<u8, ChartLoadError> {
+        self.bytes.get(self.pos).copied().ok_or(ChartLoadError::UnexpectedEof)
+    }
+
+    fn take_u8(&mut self) -> Result<u8, ChartLoadError> {
+        let byte = self.peek_u8()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take_u16(&mut self) -> Result<u16, ChartLoadError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn take_u32(&mut self) -> Result<u32, ChartLoadError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// MIDI variable-length quantity: 7 data bits per byte, high bit set on all but the last.
+    fn take_vlq(&mut self) -> Result<u64, ChartLoadError> {
+        let mut value: u64 = 0;
+        loop {
+            let byte = self.take_u8()?;
+            value = (value << 7) | (byte & 0x7F) as u64;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat_timing::Grade;
+
+    /// Format-0 SMF, 96 ticks/quarter, default tempo (120 BPM): a note-on for middle C at
+    /// tick 0, then a note-off 96 ticks (one quarter note) later.
+    fn single_note_midi_bytes() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"MThd");
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        bytes.extend_from_slice(&0u16.to_be_bytes()); // format
+        bytes.extend_from_slice(&1u16.to_be_bytes()); // track count
+        bytes.extend_from_slice(&96u16.to_be_bytes()); // division
+
+        let track_data: Vec<u8> = vec![
+            0x00, 0x90, 60, 100, // delta 0, note-on C4 vel 100
+            0x60, 0x80, 60, 0, // delta 96, note-off C4
+            0x00, 0xFF, 0x2F, 0x00, // delta 0, end of track
+        ];
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track_data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track_data);
+
+        bytes
+    }
+
+    fn config() -> BeatConfig {
+        BeatConfig {
+            bpm: 120.0,
+            offset_seconds: 0.0,
+            grace_beats: 0,
+            windows: &[(Grade::Perfect, 0.03)],
+            min_accept_grade: Grade::Good,
+            clock_jitter_threshold_seconds: 0.05,
+            clock_discontinuity_threshold_seconds: 0.25,
+        }
+    }
+
+    #[test]
+    fn load_midi_chart_maps_a_note_on_to_its_beat() {
+        let bytes = single_note_midi_bytes();
+
+        let chart = load_midi_chart(&bytes, Move::from_midi_note, &config()).unwrap();
+
+        assert_eq!(chart.expected_move(0), Some(&Move::Qoogie));
+    }
+
+    #[test]
+    fn load_midi_chart_rejects_non_midi_bytes() {
+        let result = load_midi_chart(b"not a midi file", Move::from_midi_note, &config());
+        assert_eq!(result.unwrap_err(), ChartLoadError::NotAMidiFile);
+    }
+}
@@ -5,7 +5,12 @@ use bevy::log::LogPlugin;
 use bevy::prelude::*;
 
 mod beat_timing;
-use beat_timing::{BeatConfig, BeatTracker, PressAccept};
+mod chart;
+mod tempo_analysis;
+use beat_timing::{BeatConfig, BeatTracker, ClockDelta, Grade, PressAccept, ScoreTracker};
+use chart::Chart;
+use rodio::Source;
+use tempo_analysis::TempoAnalysisConfig;
 
 fn main() {
     App::new()
@@ -20,10 +25,21 @@ fn main() {
             ..default()
         }))
         .add_systems(Startup, (setup, setup_ui))
-        .add_systems(Update, process_beat_input)
+        .add_systems(
+            Update,
+            (
+                track_audio_clock,
+                process_beat_input,
+                check_for_missed_beat,
+                metronome_tick,
+                report_score_at_song_end,
+            )
+                .chain(),
+        )
         .add_observer(combo_handler)
         .add_observer(combo_break_handler)
         .add_observer(combo_text)
+        .add_observer(metronome_flash)
         .insert_resource(CurrentCombo::default())
         .run();
 }
@@ -36,6 +52,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         bpm: 115,
         offset: 0.08,
         grace_beats: 8,
+        chart: None,
     };
 
     let _level_2 = Song {
@@ -43,6 +60,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         bpm: 124,
         offset: 0.0,
         grace_beats: 8,
+        chart: None,
     };
 
     let level_3 = Song {
@@ -50,36 +68,142 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         bpm: 132,
         offset: 0.04,
         grace_beats: 8,
+        chart: None,
     };
 
     let _tracker = BeatTracker::new(BeatConfig {
         bpm: level_3.bpm as f32,
         offset_seconds: level_3.offset,
-        leniency_seconds: LENIENCY,
         grace_beats: level_3.grace_beats,
+        windows: TIMING_WINDOWS,
+        min_accept_grade: MIN_ACCEPT_GRADE,
+        clock_jitter_threshold_seconds: CLOCK_JITTER_THRESHOLD_SECONDS,
+        clock_discontinuity_threshold_seconds: CLOCK_DISCONTINUITY_THRESHOLD_SECONDS,
     });
 
-    let control_song = Song {
+    let mut control_song = Song {
         asset_path: "audio/control.mp3".to_string(),
         bpm: 180,
         offset: 0.0,
         grace_beats: 24,
+        chart: None,
     };
 
-    let control = BeatTracker::new(BeatConfig {
-        bpm: control_song.bpm as f32,
-        offset_seconds: control_song.offset,
-        leniency_seconds: LENIENCY,
-        grace_beats: control_song.grace_beats,
-    });
+    // Prefer an automatic BPM/offset estimate over the hand-tuned `control_song` fields,
+    // falling back to them if the audio can't be decoded or the analyzer returns no estimate.
+    let control_config = analyzed_beat_config(
+        &control_song.asset_path,
+        control_song.grace_beats,
+        control_song.bpm as f32,
+        control_song.offset,
+    );
+    control_song.chart = load_chart_for(&control_song.asset_path, &control_config);
+    let control = BeatTracker::new(control_config);
 
     commands.spawn((
         AudioPlayer::new(asset_server.load(&control_song.asset_path)),
         control_song,
-        BeatTracking { tracker: control },
+        BeatTracking {
+            tracker: control,
+            score: ScoreTracker::new(),
+            next_upcoming_announce: 0,
+            summary_reported: false,
+        },
     ));
 }
 
+/// Derives a `BeatConfig` for `asset_path` via `tempo_analysis`, falling back to
+/// `fallback_bpm`/`fallback_offset` if `assume_fixed_tempo` is off, the audio can't be
+/// decoded, or the analyzer returns no estimate (too little audio, or an empty tempo range).
+fn analyzed_beat_config(
+    asset_path: &str,
+    grace_beats: usize,
+    fallback_bpm: f32,
+    fallback_offset: f32,
+) -> BeatConfig {
+    let fallback = BeatConfig {
+        bpm: fallback_bpm,
+        offset_seconds: fallback_offset,
+        grace_beats,
+        windows: TIMING_WINDOWS,
+        min_accept_grade: MIN_ACCEPT_GRADE,
+        clock_jitter_threshold_seconds: CLOCK_JITTER_THRESHOLD_SECONDS,
+        clock_discontinuity_threshold_seconds: CLOCK_DISCONTINUITY_THRESHOLD_SECONDS,
+    };
+
+    let analysis_config = TempoAnalysisConfig::default();
+    if !analysis_config.assume_fixed_tempo {
+        // The analyzer only estimates a single global tempo; without that assumption
+        // there's nothing it can reliably say, so defer entirely to manual tuning.
+        return fallback;
+    }
+
+    let Some(decoded) = decode_asset_to_mono_f32(asset_path) else {
+        return fallback;
+    };
+
+    match tempo_analysis::analyze_tempo(&decoded.samples, decoded.sample_rate, &analysis_config) {
+        Some(estimate) => estimate.into_beat_config(
+            grace_beats,
+            TIMING_WINDOWS,
+            MIN_ACCEPT_GRADE,
+            CLOCK_JITTER_THRESHOLD_SECONDS,
+            CLOCK_DISCONTINUITY_THRESHOLD_SECONDS,
+        ),
+        None => fallback,
+    }
+}
+
+struct DecodedAudio {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+/// Reads `assets/{asset_path}` from disk and decodes it to mono `f32` PCM, downmixing any
+/// extra channels by averaging. Returns `None` if the file is missing or undecodable.
+fn decode_asset_to_mono_f32(asset_path: &str) -> Option<DecodedAudio> {
+    let file = std::fs::File::open(format!("assets/{asset_path}")).ok()?;
+    let source = rodio::Decoder::new(std::io::BufReader::new(file)).ok()?;
+
+    let sample_rate = source.sample_rate();
+    let channels = source.channels().max(1) as usize;
+    let interleaved: Vec<f32> = source.convert_samples().collect();
+
+    let samples = if channels == 1 {
+        interleaved
+    } else {
+        interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect()
+    };
+
+    Some(DecodedAudio {
+        samples,
+        sample_rate,
+    })
+}
+
+/// Reads the `.mid` chart for `asset_path` (same file stem, under `assets/charts/`) and loads it against `beat_config`. Returns `None` if there's no chart
+/// for this song or it fails to parse, in which case the song simply accepts any `Move` on
+/// any beat.
+fn load_chart_for(asset_path: &str, beat_config: &BeatConfig) -> Option<Chart> {
+    let chart_path = chart_path_for(asset_path)?;
+    let bytes = std::fs::read(&chart_path).ok()?;
+    match chart::load_midi_chart(&bytes, Move::from_midi_note, beat_config) {
+        Ok(chart) => Some(chart),
+        Err(err) => {
+            warn!("Failed to parse chart {chart_path}: {err:?}");
+            None
+        }
+    }
+}
+
+fn chart_path_for(asset_path: &str) -> Option<String> {
+    let stem = std::path::Path::new(asset_path).file_stem()?.to_str()?;
+    Some(format!("assets/charts/{stem}.mid"))
+}
+
 fn setup_ui(mut commands: Commands) {
     commands.spawn((
         Node {
@@ -91,12 +215,40 @@ fn setup_ui(mut commands: Commands) {
         Text::default(),
         HintText,
     ));
+
+    commands.spawn((
+        Node {
+            width: px(24),
+            height: px(24),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.0)),
+        MetronomeIndicator,
+    ));
 }
 
+/// Brightens as the next beat approaches, driven by [`BeatEvent::Upcoming`].
+#[derive(Component)]
+struct MetronomeIndicator;
+
 #[derive(Component)]
 struct HintText;
 
-const LENIENCY: f32 = 0.1; // in seconds
+// Concentric timing windows, ascending by half-width. `judge` returns the tightest one
+// whose half-width contains the press error.
+const TIMING_WINDOWS: &[(Grade, f32)] = &[
+    (Grade::Perfect, 0.03),
+    (Grade::Great, 0.06),
+    (Grade::Good, 0.1),
+];
+const MIN_ACCEPT_GRADE: Grade = Grade::Good;
+// A Good hit still resolves the beat, but combo_handler only extends the flashy
+// Move-combo chain on tighter timing than that.
+const COMBO_MIN_GRADE: Grade = Grade::Great;
+// Tuned for a ~60fps Update loop: a frame delta of up to 0.05s (a dropped frame or two) is on
+// time, up to 0.25s is jitter, anything bigger is a seek/pause.
+const CLOCK_JITTER_THRESHOLD_SECONDS: f32 = 0.05;
+const CLOCK_DISCONTINUITY_THRESHOLD_SECONDS: f32 = 0.25;
 
 #[derive(Clone, Component)]
 struct Song {
@@ -104,18 +256,21 @@ struct Song {
     bpm: usize,
     offset: f32,        // in seconds
     grace_beats: usize, // number of beats in the start that does not count
+    // Authored beatmap, e.g. from `chart::load_midi_chart`. `None` means freeplay: any
+    // `Move` is accepted on any beat.
+    chart: Option<Chart>,
 }
 
 #[derive(Event)]
 enum BeatEvent {
-    On(Move),
+    /// An accepted on-beat press, along with the grade it was judged at so observers like
+    /// `combo_handler` can reward tighter timing.
+    On(Move, Grade),
     Off(Move),
     Missed,
-}
-enum Beat {
-    On,
-    Off,
-    Missed,
+    /// A beat is about to land; not yet judged. Drives anticipatory UI like the
+    /// metronome indicator, emitted ahead of time by `metronome_tick`.
+    Upcoming { time_until_seconds: f32 },
 }
 
 #[derive(Component, Debug, Clone, PartialEq, Eq)]
@@ -181,6 +336,22 @@ impl Display for Move {
 }
 
 impl Move {
+    /// Maps a MIDI note number to the `Move` it charts, for `chart::load_midi_chart`.
+    /// One octave of C major starting at middle C, in the same order as the QWER/ASDF keys.
+    fn from_midi_note(note: u8) -> Option<Move> {
+        match note {
+            60 => Some(Move::Qoogie), // C4
+            62 => Some(Move::Woogie), // D4
+            64 => Some(Move::Eoogie), // E4
+            65 => Some(Move::Roogie), // F4
+            67 => Some(Move::Aoogie), // G4
+            69 => Some(Move::Soogie), // A4
+            71 => Some(Move::Doogie), // B4
+            72 => Some(Move::Foogie), // C5
+            _ => None,
+        }
+    }
+
     fn get_combos(&self) -> &'static [Move] {
         use crate::Move::*;
         match self {
@@ -217,7 +388,16 @@ fn combo_break_handler(mut event: On<ComboBreakEvent>) {
 
 fn combo_handler(event: On<BeatEvent>, mut combo: ResMut<CurrentCombo>, mut commands: Commands) {
     match event.event() {
-        BeatEvent::On(mv) => {
+        BeatEvent::On(mv, grade) => {
+            // On beat, but too loose to reward with a chain continuation: tighter timing
+            // is required to keep the Move-combo alive, even though it still resolves the
+            // beat for scoring/tracking purposes.
+            if *grade < COMBO_MIN_GRADE {
+                commands.trigger(ComboBreakEvent(combo.moves.clone()));
+                combo.moves.clear();
+                return;
+            }
+
             let combo_string = combo
                 .moves
                 .iter()
@@ -241,6 +421,7 @@ fn combo_handler(event: On<BeatEvent>, mut combo: ResMut<CurrentCombo>, mut comm
             commands.trigger(ComboBreakEvent(combo.moves.clone()));
             combo.moves.clear();
         }
+        BeatEvent::Upcoming { .. } => {}
     }
 }
 
@@ -249,7 +430,7 @@ fn combo_text(
     combo: Res<CurrentCombo>,
     mut query: Query<&mut Text, With<HintText>>,
 ) {
-    if let BeatEvent::On(mv) = event.event() {
+    if let BeatEvent::On(mv, _grade) = event.event() {
         let text = query.single_mut().unwrap().into_inner();
         let combo_string = combo
             .moves
@@ -261,14 +442,31 @@ fn combo_text(
     }
 }
 
-#[derive(Component)]
-struct BeatStatistics {
-    beats: Vec<Vec<(f32, Beat)>>, // (offset, beat)
-}
-
 #[derive(Component)]
 struct BeatTracking {
     tracker: BeatTracker,
+    score: ScoreTracker,
+    /// Lowest beat index not yet announced via `BeatEvent::Upcoming`, so the same
+    /// approaching beat doesn't get re-announced every frame it stays within the horizon.
+    next_upcoming_announce: usize,
+    /// Whether `report_score_at_song_end` has already shown the results for this song.
+    summary_reported: bool,
+}
+
+// How far ahead of the audio clock to schedule the metronome/anticipatory UI.
+const METRONOME_HORIZON_SECONDS: f32 = 0.5;
+
+/// Classifies the audio clock's movement since last frame and resyncs `BeatTracker` on a
+/// large jump (seek/pause), before anything else reads `elapsed_seconds` this frame.
+fn track_audio_clock(query: Single<(&AudioSink, &mut BeatTracking)>) {
+    let (sink, mut tracking) = query.into_inner();
+    let elapsed_time = sink.position().as_secs_f32();
+
+    match tracking.tracker.observe_clock(elapsed_time) {
+        ClockDelta::OnTime => {}
+        ClockDelta::Jitter => trace!("clock jitter at {}", elapsed_time),
+        ClockDelta::Discontinuity => info!("clock discontinuity at {}, resynced", elapsed_time),
+    }
 }
 
 fn process_beat_input(
@@ -276,18 +474,27 @@ fn process_beat_input(
     query: Single<(&AudioSink, &Song, &mut BeatTracking)>,
     keys: Res<ButtonInput<KeyCode>>,
 ) {
-    let (sink, _song, mut tracking) = query.into_inner();
+    let (sink, song, mut tracking) = query.into_inner();
 
     for event in keys.get_just_pressed() {
         let elapsed_time = sink.position().as_secs_f32();
         let press = tracking.tracker.register_press(elapsed_time);
+        let pressed_move: Move = event.into();
+
+        // No chart, or the chart has nothing scheduled for this beat: anything goes.
+        let matches_chart = song
+            .chart
+            .as_ref()
+            .and_then(|chart| chart.expected_move(press.judgment.beat_index))
+            .is_none_or(|expected| *expected == pressed_move);
+        tracking.score.record_press(&press, matches_chart);
 
         // Not on beat, combo break
         if !press.judgment.on_beat {
-            commands.trigger(BeatEvent::Off(event.into()));
+            commands.trigger(BeatEvent::Off(pressed_move));
             trace!(
-                "OFF! ({:?}): {} [{}]",
-                event, press.judgment.error_seconds, press.judgment.beat_index
+                "OFF! ({:?}): {:?} {} [{}]",
+                event, press.judgment.grade, press.judgment.error_seconds, press.judgment.beat_index
             );
             continue;
         }
@@ -303,59 +510,95 @@ fn process_beat_input(
             continue;
         }
 
+        if !matches_chart {
+            commands.trigger(BeatEvent::Off(pressed_move));
+            trace!(
+                "WRONG MOVE ({:?}): chart expected a different move [{}]",
+                event, press.judgment.beat_index
+            );
+            continue;
+        }
+
         trace!(
-            "ON! ({:?}): {} [{}]",
-            event, press.judgment.error_seconds, press.judgment.beat_index
+            "ON! ({:?}): {:?} {} [{}]",
+            event, press.judgment.grade, press.judgment.error_seconds, press.judgment.beat_index
         );
-        // commands.trigger(BeatEvent::On(event.into()));
+        commands.trigger(BeatEvent::On(pressed_move, press.judgment.grade));
     }
+}
+
+fn check_for_missed_beat(
+    mut commands: Commands,
+    music_controller: Single<(&AudioSink, &Song, &mut BeatTracking)>,
+) {
+    let (sink, _song, mut tracking) = music_controller.into_inner();
 
-    // let elapsed_time = sink.position().as_secs_f32();
-
-    // for event in keys.get_just_pressed() {
-    //     let press = tracking.tracker.register_press(elapsed_time);
-    //     let beat_index = press.judgment.beat_index;
-    //     let on_beat = press.judgment.on_beat;
-    //     let offset = press.judgment.error_seconds;
-
-    //     if press.accept == PressAccept::Duplicate {
-    //         info!("DUPLICATE PRESS ({:?}) beat [{}]", event, beat_index);
-    //         continue;
-    //     }
-
-    //     if beat_stats.beats.len() <= beat_index {
-    //         beat_stats.beats.resize_with(beat_index + 1, Vec::new);
-    //     }
-
-    //     if on_beat {
-    //         info!("ON! ({:?}): {} [{}]", event, offset, beat_index);
-    //         beat_stats.beats[beat_index].push((offset, Beat::On));
-    //         commands.trigger(BeatEvent::On((*event).into()));
-    //     } else {
-    //         info!("OFF! ({:?}): {} [{}]", event, offset, beat_index);
-    //         beat_stats.beats[beat_index].push((offset, Beat::Off));
-    //         commands.trigger(BeatEvent::Off((*event).into()));
-    //     }
-    // }
+    let elapsed_time = sink.position().as_secs_f32();
+    let missed = tracking.tracker.poll_missed(elapsed_time);
+
+    for miss in missed {
+        trace!("MISSED! {}, index: {}", miss.late_by_seconds, miss.beat_index);
+        tracking.score.record_miss(&miss);
+        commands.trigger(BeatEvent::Missed);
+    }
+}
+
+/// Shows the results screen once a song's audio has finished playing, computing the
+/// `ScoreSummary` lazily at that point rather than every frame.
+fn report_score_at_song_end(
+    music_controller: Single<(&AudioSink, &mut BeatTracking)>,
+    mut hint_text: Query<&mut Text, With<HintText>>,
+) {
+    let (sink, mut tracking) = music_controller.into_inner();
+    if tracking.summary_reported || !sink.empty() {
+        return;
+    }
+    tracking.summary_reported = true;
+
+    let summary = tracking.score.summary();
+    info!(
+        "Song finished! hits: {}, wrong: {}, missed: {}, accuracy: {:.1}%, longest combo: {}",
+        summary.on_beat_hits, summary.wrong_presses, summary.misses, summary.accuracy_percent,
+        summary.longest_combo
+    );
+
+    if let Ok(mut text) = hint_text.single_mut() {
+        **text = format!(
+            "Results\naccuracy: {:.1}%\nlongest combo: {}\nmean error: {:.3}s",
+            summary.accuracy_percent, summary.longest_combo, summary.mean_abs_error_seconds
+        );
+    }
+}
+
+/// Runs ahead of the audio clock so UI (the metronome indicator) can anticipate a beat
+/// instead of only reacting once it has passed.
+fn metronome_tick(mut commands: Commands, music_controller: Single<(&AudioSink, &mut BeatTracking)>) {
+    let (sink, mut tracking) = music_controller.into_inner();
+    let elapsed_time = sink.position().as_secs_f32();
+
+    for beat in tracking
+        .tracker
+        .upcoming(elapsed_time, METRONOME_HORIZON_SECONDS)
+    {
+        if beat.beat_index < tracking.next_upcoming_announce {
+            continue;
+        }
+        tracking.next_upcoming_announce = beat.beat_index + 1;
+        commands.trigger(BeatEvent::Upcoming {
+            time_until_seconds: beat.time_until_seconds,
+        });
+    }
 }
 
-// fn check_for_missed_beat(
-//     mut commands: Commands,
-//     music_controller: Single<(&AudioSink, &Song, &mut BeatStatistics, &mut BeatTracking)>,
-// ) {
-//     let (sink, _song, mut stats, mut tracking) = music_controller.into_inner();
-
-//     let elapsed_time = sink.position().as_secs_f32();
-
-//     for miss in tracking.tracker.poll_missed(elapsed_time) {
-//         let beat_index = miss.beat_index;
-//         let offset = miss.late_by_seconds;
-//         trace!("MISSED! {}, index: {}", offset, beat_index);
-
-//         if stats.beats.len() <= beat_index {
-//             stats.beats.resize_with(beat_index + 1, Vec::new);
-//         }
-//         stats.beats[beat_index].push((offset, Beat::Missed));
-//         commands.trigger(BeatEvent::Missed);
-//     }
-// }
+fn metronome_flash(
+    event: On<BeatEvent>,
+    mut query: Query<&mut BackgroundColor, With<MetronomeIndicator>>,
+) {
+    if let BeatEvent::Upcoming { time_until_seconds } = event.event() {
+        if let Ok(mut color) = query.single_mut() {
+            // Brighter the closer the beat is; a shrinking-ring effect in miniature.
+            let closeness = (1.0 - (time_until_seconds / METRONOME_HORIZON_SECONDS)).clamp(0.0, 1.0);
+            *color = BackgroundColor(Color::srgba(1.0, 1.0, 1.0, closeness));
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Automatic BPM/offset estimation from decoded audio, so a [`crate::beat_timing::BeatConfig`]
+//! doesn't have to be hand-tuned per song.
+//!
+//! The approach is the standard one for beat-grid-with-offset estimation:
+//! 1. Reduce the waveform to an onset (energy novelty) envelope over short hop windows.
+//! 2. Autocorrelate that envelope across a candidate tempo range to find the dominant period.
+//! 3. Cross-correlate a pulse train at that period against the envelope to recover phase/offset.
+//!
+//! `main::analyzed_beat_config` decodes a song's audio file and calls into here to derive
+//! its `BeatConfig` instead of hand-tuning `bpm`/`offset_seconds`.
+
+use crate::beat_timing::{BeatConfig, Grade};
+
+#[derive(Debug, Clone, Copy)]
+pub struct TempoAnalysisConfig {
+    /// Lower bound of the candidate tempo search range, in BPM.
+    pub min_bpm: f32,
+    /// Upper bound of the candidate tempo search range, in BPM.
+    pub max_bpm: f32,
+    /// Number of PCM samples per onset-envelope hop window.
+    pub hop_size: usize,
+    /// Round the estimated BPM to the nearest integer.
+    pub snap_to_integer_bpm: bool,
+    /// Assume the track holds one constant tempo throughout, rather than tracking
+    /// tempo changes over time (which this analyzer does not support).
+    pub assume_fixed_tempo: bool,
+}
+
+impl Default for TempoAnalysisConfig {
+    fn default() -> Self {
+        Self {
+            min_bpm: 70.0,
+            max_bpm: 180.0,
+            hop_size: 512,
+            snap_to_integer_bpm: true,
+            assume_fixed_tempo: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoEstimate {
+    pub bpm: f32,
+    pub offset_seconds: f32,
+}
+
+impl TempoEstimate {
+    /// Bundle this estimate with the rest of the timing-window tuning to produce a
+    /// ready-to-use [`BeatConfig`].
+    pub fn into_beat_config(
+        self,
+        grace_beats: usize,
+        windows: &'static [(Grade, f32)],
+        min_accept_grade: Grade,
+        clock_jitter_threshold_seconds: f32,
+        clock_discontinuity_threshold_seconds: f32,
+    ) -> BeatConfig {
+        BeatConfig {
+            bpm: self.bpm,
+            offset_seconds: self.offset_seconds,
+            grace_beats,
+            windows,
+            min_accept_grade,
+            clock_jitter_threshold_seconds,
+            clock_discontinuity_threshold_seconds,
+        }
+    }
+}
+
+/// Estimate BPM and beat-offset from mono PCM samples.
+///
+/// Returns `None` if `samples` is too short to form at least a few onset-envelope hops,
+/// or if `config` describes an empty/invalid tempo search range.
+pub fn analyze_tempo(
+    samples: &[f32],
+    sample_rate: u32,
+    config: &TempoAnalysisConfig,
+) -> Option<TempoEstimate> {
+    if config.hop_size == 0 || config.min_bpm <= 0.0 || config.max_bpm <= config.min_bpm {
+        return None;
+    }
+
+    let hop_duration = config.hop_size as f32 / sample_rate as f32;
+    let envelope = onset_envelope(samples, config.hop_size);
+    if envelope.len() < 4 {
+        return None;
+    }
+
+    // Candidate lag range (in hops) corresponding to the BPM search range.
+    let min_lag = ((60.0 / config.max_bpm) / hop_duration).floor().max(1.0) as usize;
+    let max_lag = ((60.0 / config.min_bpm) / hop_duration).ceil() as usize;
+    let max_lag = max_lag.min(envelope.len().saturating_sub(1));
+    if min_lag >= max_lag {
+        return None;
+    }
+
+    let period_hops = dominant_period_hops(&envelope, min_lag, max_lag)?;
+    let mut bpm = 60.0 / (period_hops as f32 * hop_duration);
+    if config.snap_to_integer_bpm {
+        bpm = bpm.round();
+    }
+
+    let phase_hops = best_phase_hops(&envelope, period_hops);
+    let beat_period = period_hops as f32 * hop_duration;
+    let phase_seconds = phase_hops as f32 * hop_duration;
+    // `BeatConfig::beat_time_seconds` computes `k * period - offset_seconds`, so the beat
+    // grid lands on an onset at `phase_seconds` when `offset_seconds` is the *complement* of
+    // the phase within the period, not the phase itself.
+    let offset_seconds = (beat_period - phase_seconds) % beat_period;
+
+    Some(TempoEstimate {
+        bpm,
+        offset_seconds,
+    })
+}
+
+/// Half-wave rectified energy-novelty envelope: one value per hop, representing how much
+/// louder this hop was than the previous one (onsets show up as positive spikes).
+fn onset_envelope(samples: &[f32], hop_size: usize) -> Vec<f32> {
+    let energies: Vec<f32> = samples
+        .chunks(hop_size)
+        .map(|chunk| chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32)
+        .collect();
+
+    let mut envelope = Vec::with_capacity(energies.len());
+    let mut prev = 0.0;
+    for energy in energies {
+        envelope.push((energy - prev).max(0.0));
+        prev = energy;
+    }
+    envelope
+}
+
+/// Find the lag (in hops) within `[min_lag, max_lag]` that maximizes the autocorrelation
+/// of `envelope` with itself, i.e. the most likely beat period.
+fn dominant_period_hops(envelope: &[f32], min_lag: usize, max_lag: usize) -> Option<usize> {
+    (min_lag..=max_lag)
+        .map(|lag| (lag, autocorrelation(envelope, lag)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(lag, _)| lag)
+}
+
+fn autocorrelation(envelope: &[f32], lag: usize) -> f32 {
+    if lag == 0 || lag >= envelope.len() {
+        return 0.0;
+    }
+    envelope[lag..]
+        .iter()
+        .zip(envelope.iter())
+        .map(|(a, b)| a * b)
+        .sum()
+}
+
+/// Find the phase (in hops, within `[0, period_hops)`) that best aligns a pulse train of
+/// period `period_hops` with the onset envelope.
+fn best_phase_hops(envelope: &[f32], period_hops: usize) -> usize {
+    (0..period_hops)
+        .map(|phase| {
+            let score: f32 = envelope
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| i % period_hops == phase)
+                .map(|(_, e)| e)
+                .sum();
+            (phase, score)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(phase, _)| phase)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beat_timing::Grade;
+
+    /// Synthetic onset train at true period 0.5s, offset 0.1s (an onset every 0.5s starting
+    /// at t=0.1s), sampled at 1000Hz.
+    fn synthetic_onset_train() -> (Vec<f32>, u32) {
+        let sample_rate = 1000;
+        let period_samples = 500;
+        let first_onset_sample = 100;
+        let periods = 8;
+
+        let mut samples = vec![0.0; periods * period_samples + period_samples];
+        for k in 0..periods {
+            samples[first_onset_sample + k * period_samples] = 1.0;
+        }
+        (samples, sample_rate)
+    }
+
+    #[test]
+    fn analyze_tempo_recovers_bpm_and_aligns_offset_to_the_onsets() {
+        let (samples, sample_rate) = synthetic_onset_train();
+        let config = TempoAnalysisConfig {
+            min_bpm: 90.0,
+            max_bpm: 150.0,
+            hop_size: 50,
+            snap_to_integer_bpm: true,
+            assume_fixed_tempo: true,
+        };
+
+        let estimate = analyze_tempo(&samples, sample_rate, &config).expect("should find a tempo");
+        assert_eq!(estimate.bpm, 120.0);
+
+        let beat_config = estimate.into_beat_config(0, &[(Grade::Perfect, 0.03)], Grade::Good, 0.05, 0.25);
+
+        // The real onset at t=0.1s should fall on the estimated beat grid, not half a
+        // period away from it.
+        let judgment = beat_config.judge(0.1);
+        assert_eq!(judgment.grade, Grade::Perfect);
+    }
+}